@@ -26,7 +26,7 @@ fn main() {
     spade.next(0);
     println!("{:?}", spade);
 
-    for (pattern, support) in spade.report() {
+    for (pattern, support) in spade.report(true) {
         println!("Pattern: {:?}, Support: {}", pattern, support);
     }
 }
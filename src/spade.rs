@@ -1,14 +1,132 @@
 use bit_set::BitSet;
+use indexmap::IndexMap;
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
 use std::fmt;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
 use std::iter::FromIterator;
-use std::sync::RwLock;
 
 pub type EventSet = BitSet;
 type Pattern = Vec<EventSet>;
 type List = Vec<Record>;
 
+/// A `HashMap` keyed with the fast, non-cryptographic [`FxHasher`].
+type FxHashMap<K, V> = HashMap<K, V, FxBuildHasher>;
+/// An insertion-ordered [`IndexMap`] keyed with [`FxHasher`], used for `store`.
+type FxIndexMap<K, V> = IndexMap<K, V, FxBuildHasher>;
+type FxBuildHasher = BuildHasherDefault<FxHasher>;
+
+/// A non-cryptographic hasher ported from rustc's `fx` module; the pattern keys
+/// are never adversarial input, so SipHash's DoS protection is not needed.
+#[derive(Default)]
+struct FxHasher {
+    hash: u64,
+}
+
+const FX_SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+impl FxHasher {
+    #[inline]
+    fn add_word(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(FX_SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    #[inline]
+    fn write(&mut self, mut bytes: &[u8]) {
+        while bytes.len() >= 8 {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&bytes[..8]);
+            self.add_word(u64::from_le_bytes(word));
+            bytes = &bytes[8..];
+        }
+        if !bytes.is_empty() {
+            let mut word = [0u8; 8];
+            word[..bytes.len()].copy_from_slice(bytes);
+            self.add_word(u64::from_le_bytes(word));
+        }
+    }
+
+    #[inline]
+    fn write_u32(&mut self, word: u32) {
+        self.add_word(word as u64);
+    }
+
+    #[inline]
+    fn write_u64(&mut self, word: u64) {
+        self.add_word(word);
+    }
+
+    #[inline]
+    fn write_usize(&mut self, word: usize) {
+        self.add_word(word as u64);
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// A 128-bit hash of a [`Pattern`], folded from the per-`EventSet` [`FxHasher`]
+/// digests. It only accelerates hashing; [`Key`] still compares the real
+/// `Pattern` for equality, so a collision never merges two distinct patterns.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+struct Fingerprint(u64, u64);
+
+impl Fingerprint {
+    fn of(pattern: &[EventSet]) -> Self {
+        let mut h1: u64 = 0;
+        let mut h2: u64 = 0;
+        for event in pattern {
+            let mut hasher = FxHasher::default();
+            for word in event.get_ref().blocks() {
+                hasher.write_u32(word);
+            }
+            let x = hasher.finish();
+            h1 = h1.wrapping_mul(3).wrapping_add(x);
+            h2 ^= x.rotate_left(32);
+        }
+        Fingerprint(h1, h2)
+    }
+}
+
+/// A `Pattern` map key whose hash is its precomputed [`Fingerprint`] but whose
+/// equality is the real `Pattern`, so hashing stays cheap on the join hot path
+/// while fingerprint collisions are resolved correctly instead of silently.
+#[derive(Clone, Debug)]
+struct Key {
+    fingerprint: Fingerprint,
+    pattern: Pattern,
+}
+
+impl Key {
+    fn new(pattern: Pattern) -> Self {
+        let fingerprint = Fingerprint::of(&pattern);
+        Self {
+            fingerprint,
+            pattern,
+        }
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.fingerprint.0.hash(state);
+        self.fingerprint.1.hash(state);
+    }
+}
+
 /// Record contains sid and eid of an EventSet
 #[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Record {
@@ -53,15 +171,15 @@ impl Record {
 ///
 /// ```
 pub struct Spade {
-    stack: HashMap<Pattern, List>,
-    store: HashMap<Pattern, List>,
+    stack: FxHashMap<Key, List>,
+    store: FxIndexMap<Key, List>,
 }
 
 impl Spade {
     pub fn new() -> Self {
         Self {
-            stack: HashMap::new(),
-            store: HashMap::new(),
+            stack: FxHashMap::default(),
+            store: FxIndexMap::default(),
         }
     }
 
@@ -69,32 +187,32 @@ impl Spade {
         for event_id in event_set.iter() {
             let mut event = BitSet::new();
             event.insert(event_id);
-            self.stack.entry(vec![event]).or_default().push(record);
+            self.stack.entry(Key::new(vec![event])).or_default().push(record);
         }
     }
 
-    fn candidate(&self, min_sup: usize) -> Vec<(&Pattern, &Pattern)> {
+    fn candidate(&self, min_sup: usize) -> Vec<(&Key, &Key)> {
         let mut result = Vec::new();
 
-        let patterns: Vec<&Pattern> = self
+        let keys: Vec<&Key> = self
             .stack
             .iter()
-            .filter_map(|(pattern, list)| {
+            .filter_map(|(key, list)| {
                 if list.len() > min_sup {
-                    Some(pattern)
+                    Some(key)
                 } else {
                     None
                 }
             })
             .collect();
 
-        let mut iters = patterns.into_iter();
-        while let Some(prefix_pattern) = iters.next() {
-            result.push((prefix_pattern, prefix_pattern));
+        let mut iters = keys.into_iter();
+        while let Some(prefix_key) = iters.next() {
+            result.push((prefix_key, prefix_key));
 
             let mut iters = iters.clone();
-            while let Some(suffix_pattern) = iters.next() {
-                result.push((prefix_pattern, suffix_pattern));
+            while let Some(suffix_key) = iters.next() {
+                result.push((prefix_key, suffix_key));
             }
         }
 
@@ -103,16 +221,24 @@ impl Spade {
 
     /// Enumerate the next step of BFS, with min_sup pruning
     pub fn next(&mut self, min_sup: usize) {
-        let result = RwLock::new(HashMap::new());
-
-        self.candidate(min_sup)
+        // Fold each worker's share of candidate joins into a thread-local map,
+        // then reduce the maps together. When several candidate pairs produce
+        // the same pattern, the list from the earliest pair in candidate order
+        // is kept: `or_insert_with` keeps the first within a fold, and reduce
+        // keeps the left-hand map's entry. Lists that different pairs produce
+        // for the same pattern are not compared or merged.
+        let result = self
+            .candidate(min_sup)
             .into_par_iter()
-            .for_each(|(pattern_a, pattern_b)| {
+            .fold(FxHashMap::default, |mut result, (key_a, key_b)| {
+                let pattern_a = &key_a.pattern;
+                let pattern_b = &key_b.pattern;
+
                 let last_idx_a = pattern_a.len() - 1;
                 let last_idx_b = pattern_b.len() - 1;
 
-                let list_a = &self.stack[pattern_a];
-                let list_b = &self.stack[pattern_b];
+                let list_a = &self.stack[key_a];
+                let list_b = &self.stack[key_b];
 
                 let prefix_a = &pattern_a[..last_idx_a];
                 let prefix_b = &pattern_b[..last_idx_b];
@@ -125,36 +251,27 @@ impl Spade {
                     // Produce P -> A -> B
                     let mut pattern = pattern_a.clone();
                     pattern.push(suffix_b.clone());
-
-                    // Check if already calc
-                    if !result.read().unwrap().contains_key(&pattern) {
-                        let list = join_extend(list_a, list_b);
-                        result.write().unwrap().insert(pattern, list);
-                    }
+                    result
+                        .entry(Key::new(pattern))
+                        .or_insert_with(|| join_extend(list_a, list_b));
 
                     // Assume P -> A & P -> B, A != B
                     if pattern_a != pattern_b {
                         // Produce P -> B -> A
                         let mut pattern = pattern_b.clone();
                         pattern.push(suffix_a.clone());
-
-                        // Check if already calc
-                        if !result.read().unwrap().contains_key(&pattern) {
-                            let list = join_extend(list_b, list_a);
-                            result.write().unwrap().insert(pattern, list);
-                        }
+                        result
+                            .entry(Key::new(pattern))
+                            .or_insert_with(|| join_extend(list_b, list_a));
 
                         // Produce P -> AB
                         let mut pattern = prefix_a.to_vec();
                         let mut last = suffix_a.clone();
                         last.union_with(suffix_b);
                         pattern.push(last);
-
-                        // Check if already calc
-                        if !result.read().unwrap().contains_key(&pattern) {
-                            let list = join_expand(list_a, list_b);
-                            result.write().unwrap().insert(pattern, list);
-                        }
+                        result
+                            .entry(Key::new(pattern))
+                            .or_insert_with(|| join_expand(list_a, list_b));
                     }
                 }
 
@@ -163,50 +280,140 @@ impl Spade {
                     // Produce PA -> B
                     let mut pattern = pattern_a.clone();
                     pattern.push(suffix_b.clone());
-
-                    // Check if already calc
-                    if !result.read().unwrap().contains_key(&pattern) {
-                        let list = join_extend(list_a, list_b);
-                        result.write().unwrap().insert(pattern, list);
-                    }
+                    result
+                        .entry(Key::new(pattern))
+                        .or_insert_with(|| join_extend(list_a, list_b));
                 }
 
                 if last_idx_b + 1 == last_idx_a && &prefix_a[..last_idx_a - 1] == prefix_b {
                     // Produce PA -> B
                     let mut pattern = pattern_b.clone();
                     pattern.push(suffix_a.clone());
+                    result
+                        .entry(Key::new(pattern))
+                        .or_insert_with(|| join_extend(list_b, list_a));
+                }
 
-                    // Check if already calc
-                    if !result.read().unwrap().contains_key(&pattern) {
-                        let list = join_extend(list_b, list_a);
-                        result.write().unwrap().insert(pattern, list);
-                    }
+                result
+            })
+            .reduce(FxHashMap::default, |mut acc, other| {
+                for (key, list) in other {
+                    acc.entry(key).or_insert(list);
                 }
+                acc
             });
 
-        let stack = result.into_inner().unwrap();
-        self.store.extend(stack.clone().into_iter());
+        // Record this level in a deterministic order so that `store`'s
+        // insertion order is reproducible across runs regardless of the
+        // thread-dependent fold/reduce iteration order.
+        let mut result: Vec<(Key, List)> = result.into_iter().collect();
+        result.par_sort_unstable_by(|a, b| a.0.pattern.cmp(&b.0.pattern));
+
+        let mut stack = FxHashMap::default();
+        for (key, list) in result {
+            self.store.insert(key.clone(), list.clone());
+            stack.insert(key, list);
+        }
         self.stack = stack;
     }
 
     /// Produce an impl Iterator<Item = (Pattern, usize)> of each Pattern and Support
-    pub fn report(&self) -> impl Iterator<Item = (Pattern, usize)> {
-        let mut result: Vec<_> = self
-            .store
-            .par_iter()
-            .map(|(k, v)| {
-                let pattern = k.clone();
+    ///
+    /// With `stable` set, `store`'s insertion order — fixed deterministically
+    /// when each BFS level is recorded — is used as the final tie-breaker after
+    /// `(sort_key, support)`, so equal-ranked patterns report in the same order
+    /// on every run.
+    pub fn report(&self, stable: bool) -> impl Iterator<Item = (Pattern, usize)> {
+        let mut result: Vec<_> = (0..self.store.len())
+            .into_par_iter()
+            .map(|index| {
+                let (key, v) = self.store.get_index(index).unwrap();
+                let pattern = key.pattern.clone();
                 let support = v.len();
-                let sort_key = k.iter().map(|x| x.iter().count()).product();
-                (pattern, support, sort_key)
+                let sort_key = pattern.iter().map(|x| x.iter().count()).product();
+                (pattern, support, sort_key, index)
             })
             .collect();
-        result.par_sort_unstable_by_key(|&(_, support, sort_key): &(_, usize, usize)| {
-            (-(sort_key as isize), -(support as isize))
-        });
+        if stable {
+            result.par_sort_unstable_by_key(
+                |&(_, support, sort_key, index): &(_, usize, usize, usize)| {
+                    (-(sort_key as isize), -(support as isize), index)
+                },
+            );
+        } else {
+            result.par_sort_unstable_by_key(|&(_, support, sort_key, _): &(_, usize, usize, usize)| {
+                (-(sort_key as isize), -(support as isize))
+            });
+        }
         result
             .into_iter()
-            .map(|(pattern, support, _)| (pattern, support))
+            .map(|(pattern, support, _, _)| (pattern, support))
+    }
+
+    /// Report only the `k` most significant patterns, using a bounded min-heap
+    /// instead of sorting the whole `store`.
+    ///
+    /// The ordering matches [`Spade::report`] run with `stable` — including the
+    /// insertion-order tie-breaker — so `report_top_k(k)` is always the first
+    /// `k` of `report(true)`, but keeping just the top `k` runs in O(n log k)
+    /// rather than O(n log n).
+    pub fn report_top_k(&self, k: usize) -> impl Iterator<Item = (Pattern, usize)> {
+        let scored: Vec<Ranked> = (0..self.store.len())
+            .into_par_iter()
+            .map(|index| {
+                let (key, v) = self.store.get_index(index).unwrap();
+                let pattern = key.pattern.clone();
+                let support = v.len();
+                let sort_key = pattern.iter().map(|x| x.iter().count()).product();
+                Ranked {
+                    sort_key,
+                    support,
+                    index,
+                    pattern,
+                }
+            })
+            .collect();
+
+        // A min-heap of at most `k` entries: once it overflows, drop the
+        // weakest so only the strongest `k` survive.
+        let mut heap: BinaryHeap<Reverse<Ranked>> = BinaryHeap::with_capacity(k + 1);
+        for item in scored {
+            heap.push(Reverse(item));
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+
+        let mut result: Vec<Ranked> = heap.into_iter().map(|Reverse(item)| item).collect();
+        result.par_sort_unstable_by(|a, b| b.cmp(a));
+        result
+            .into_iter()
+            .map(|Ranked { pattern, support, .. }| (pattern, support))
+    }
+}
+
+/// A scored pattern ordered by significance: higher `(sort_key, support)` and,
+/// on a tie, earlier `store` insertion (`index`) ranks greater.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Ranked {
+    sort_key: usize,
+    support: usize,
+    index: usize,
+    pattern: Pattern,
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key
+            .cmp(&other.sort_key)
+            .then(self.support.cmp(&other.support))
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -276,32 +483,42 @@ fn join_expand(a: &List, b: &List) -> List {
 
 impl FromIterator<(Record, EventSet)> for Spade {
     fn from_iter<I: IntoIterator<Item = (Record, EventSet)>>(iter: I) -> Self {
-        let mut stack: HashMap<Pattern, List> = HashMap::new();
+        let mut grouped: FxHashMap<Pattern, List> = FxHashMap::default();
         for (record, event_set) in iter {
             for event_id in event_set.iter() {
                 let mut event = EventSet::new();
                 event.insert(event_id);
 
-                stack.entry(vec![event]).or_default().push(record);
+                grouped.entry(vec![event]).or_default().push(record);
             }
         }
-        stack.values_mut().for_each(|v| {
-            v.par_sort_unstable();
-            v.dedup();
-        });
-        let store = stack.clone();
-        Self { stack, store }
+
+        // Record the first level in a deterministic order, so `store` reports
+        // reproducibly (see `next`).
+        let mut grouped: Vec<(Pattern, List)> = grouped.into_iter().collect();
+        grouped.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let mut spade = Spade::new();
+        for (pattern, mut list) in grouped {
+            list.par_sort_unstable();
+            list.dedup();
+
+            let key = Key::new(pattern);
+            spade.store.insert(key.clone(), list.clone());
+            spade.stack.insert(key, list);
+        }
+        spade
     }
 }
 
 impl fmt::Debug for Spade {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "\nSpade Stack\n\n")?;
-        let mut patterns: Vec<&Pattern> = self.stack.keys().collect();
+        let mut patterns: Vec<&Pattern> = self.stack.keys().map(|key| &key.pattern).collect();
         patterns.par_sort_unstable();
 
         for pattern in patterns {
-            let list = &self.stack[pattern];
+            let list = &self.stack[&Key::new(pattern.clone())];
             if list.len() < 30 {
                 continue;
             }
@@ -325,7 +542,7 @@ impl fmt::Debug for Spade {
 
 #[cfg(test)]
 mod tests {
-    use super::{BitSet, Record, Spade};
+    use super::{BitSet, Key, Record, Spade};
 
     const DATA: &[(u32, i32, u8)] = &[
         (0, 1, 0b10000000),
@@ -350,8 +567,29 @@ mod tests {
         event_set.insert(0);
 
         assert_eq!(
-            spade.stack[&vec![event_set]],
+            spade.stack[&Key::new(vec![event_set])],
             vec![Record::new(0, 1), Record::new(0, 2), Record::new(2, 1)]
         );
     }
+
+    #[test]
+    fn test_report_top_k_matches_report_prefix() {
+        let mut spade: Spade = DATA
+            .iter()
+            .map(|&(sid, eid, event_set)| {
+                let record = Record::new(sid, eid);
+                let events = BitSet::from_bytes(&[event_set]);
+                (record, events)
+            })
+            .collect();
+
+        spade.next(0);
+        spade.next(0);
+
+        let full: Vec<_> = spade.report(true).collect();
+        let k = 3;
+        let expected: Vec<_> = full.into_iter().take(k).collect();
+
+        assert_eq!(spade.report_top_k(k).collect::<Vec<_>>(), expected);
+    }
 }